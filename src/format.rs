@@ -0,0 +1,194 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+
+/// Archive/codec format inferred from a path's extension or, on read, from
+/// the source's magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    TarBz2,
+    Gz,
+    Zst,
+    Bz2,
+}
+
+impl Format {
+    /// Infer a format from a path's extension(s).
+    pub fn from_extension(path: &Path) -> Option<Format> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar.zst") {
+            Some(Format::TarZst)
+        } else if name.ends_with(".tar.bz2") {
+            Some(Format::TarBz2)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".gz") {
+            Some(Format::Gz)
+        } else if name.ends_with(".zst") {
+            Some(Format::Zst)
+        } else if name.ends_with(".bz2") {
+            Some(Format::Bz2)
+        } else {
+            None
+        }
+    }
+
+    /// Infer a format from the leading bytes of a source archive.
+    pub fn from_magic(bytes: &[u8]) -> Option<Format> {
+        if bytes.starts_with(&[0x50, 0x4b, 0x03, 0x04]) || bytes.starts_with(&[0x50, 0x4b, 0x05, 0x06]) {
+            Some(Format::Zip)
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gz)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zst)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Format::Bz2)
+        } else if bytes.len() > 262 && &bytes[257..262] == b"ustar" {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Sniff a format from a file's magic bytes, for use when the extension is
+/// missing or unrecognized.
+pub fn sniff_magic(path: &Path) -> Result<Option<Format>, Error> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buf = [0u8; 512];
+    let n = file.read(&mut buf)?;
+    Ok(Format::from_magic(&buf[..n]))
+}
+
+/// The output path for a bare codec stream (`.gz`/`.zst`/`.bz2`) when the
+/// caller didn't supply one explicitly: the archive path with its codec
+/// extension stripped.
+pub fn default_output_path(archive: &Path, format: Format) -> Result<PathBuf, Error> {
+    let ext = match format {
+        Format::Gz => ".gz",
+        Format::Zst => ".zst",
+        Format::Bz2 => ".bz2",
+        _ => bail!("{format:?} has no bare codec extension"),
+    };
+    let name = archive
+        .to_str()
+        .with_context(|| format!("{archive:?}: non UTF-8 path"))?;
+    match name.strip_suffix(ext) {
+        Some(stripped) => Ok(PathBuf::from(stripped)),
+        None => bail!("{archive:?}: expected a {ext} extension"),
+    }
+}
+
+fn tar_writer(dst: &Path, codec: Option<Format>) -> Result<Box<dyn std::io::Write>, Error> {
+    let file = std::fs::File::create(dst)?;
+    let writer: Box<dyn std::io::Write> = match codec {
+        Some(Format::Gz) => Box::new(flate2::write::GzEncoder::new(file, flate2::Compression::default())),
+        Some(Format::Zst) => Box::new(zstd::stream::Encoder::new(file, 0)?.auto_finish()),
+        Some(Format::Bz2) => Box::new(bzip2::write::BzEncoder::new(file, bzip2::Compression::default())),
+        Some(other) => bail!("{other:?} is not a tar codec"),
+        None => Box::new(file),
+    };
+    Ok(writer)
+}
+
+fn tar_reader(archive: &Path, codec: Option<Format>) -> Result<Box<dyn Read>, Error> {
+    let file = std::fs::File::open(archive)?;
+    let reader: Box<dyn Read> = match codec {
+        Some(Format::Gz) => Box::new(flate2::read::GzDecoder::new(file)),
+        Some(Format::Zst) => Box::new(zstd::stream::Decoder::new(file)?),
+        Some(Format::Bz2) => Box::new(bzip2::read::BzDecoder::new(file)),
+        Some(other) => bail!("{other:?} is not a tar codec"),
+        None => Box::new(file),
+    };
+    Ok(reader)
+}
+
+/// Write `src` (a file or a directory tree) as a tar stream at `dst`,
+/// optionally piped through a compression codec.
+pub fn write_tar(src: &Path, dst: &Path, codec: Option<Format>) -> Result<(), Error> {
+    let writer = tar_writer(dst, codec)?;
+    let mut builder = tar::Builder::new(writer);
+
+    if src.is_dir() {
+        builder.append_dir_all(".", src)?;
+    } else {
+        let name = src
+            .file_name()
+            .with_context(|| format!("{src:?}: no file name"))?;
+        builder.append_path_with_name(src, name)?;
+    }
+
+    builder.finish()?;
+    Ok(())
+}
+
+/// Unpack a tar stream at `archive`, optionally piped through a
+/// decompression codec, into `output_dir` (defaulting to the current
+/// directory).
+pub fn extract_tar(archive: &Path, output_dir: Option<&Path>, codec: Option<Format>) -> Result<(), Error> {
+    let reader = tar_reader(archive, codec)?;
+    let mut tar = tar::Archive::new(reader);
+    tar.unpack(output_dir.unwrap_or_else(|| Path::new(".")))?;
+    Ok(())
+}
+
+/// Compress `src` as a bare codec stream (`.gz`/`.zst`/`.bz2`, no tar
+/// container) at `dst`.
+pub fn write_codec_stream(src: &Path, dst: &Path, format: Format) -> Result<(), Error> {
+    let mut input = std::fs::File::open(src)?;
+    let output = std::fs::File::create(dst)?;
+
+    match format {
+        Format::Gz => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            std::io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Format::Zst => {
+            let mut encoder = zstd::stream::Encoder::new(output, 0)?;
+            std::io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+        }
+        Format::Bz2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(output, bzip2::Compression::default());
+            std::io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+        }
+        other => bail!("{other:?} is not a bare codec format"),
+    }
+
+    Ok(())
+}
+
+/// Decompress a bare codec stream at `src` into `dst`.
+pub fn extract_codec_stream(src: &Path, dst: &Path, format: Format) -> Result<(), Error> {
+    let input = std::fs::File::open(src)?;
+    let mut output = std::fs::File::create(dst)?;
+
+    match format {
+        Format::Gz => {
+            let mut decoder = flate2::read::GzDecoder::new(input);
+            std::io::copy(&mut decoder, &mut output)?;
+        }
+        Format::Zst => {
+            let mut decoder = zstd::stream::Decoder::new(input)?;
+            std::io::copy(&mut decoder, &mut output)?;
+        }
+        Format::Bz2 => {
+            let mut decoder = bzip2::read::BzDecoder::new(input);
+            std::io::copy(&mut decoder, &mut output)?;
+        }
+        other => bail!("{other:?} is not a bare codec format"),
+    }
+
+    Ok(())
+}
@@ -1,9 +1,12 @@
-use std::{io::{Read, Write}, path::PathBuf};
+use std::{io::{Read, Write}, path::{Path, PathBuf}};
 use anyhow::{bail, Error, Context};
 use env_logger;
-use log::info;
+use log::{info, warn};
 use clap::{Parser, Subcommand};
 
+mod format;
+use format::Format;
+
 
 fn main() -> Result<(), Error> {
     env_logger::init();
@@ -13,8 +16,67 @@ fn main() -> Result<(), Error> {
     info!("Args: {args:?}");
 
     match args.commands {
-        Commands::Zip { src, dst, method, mode, chunk, password } => create_archive(src, dst, method, mode, chunk, password),
-        Commands::Unzip { archive, output_dir } => extract_archive(archive, output_dir),
+        Commands::Zip { src, dst, method, mode, chunk, password, level, zopfli, zopfli_iterations } => {
+            let level = resolve_compression_level(level, zopfli, zopfli_iterations);
+            create_archive_dispatch(src, dst, method, mode, chunk, password, level)
+        },
+        Commands::Unzip { archive, output_dir, password, stdin } => {
+            if stdin {
+                if password.is_some() {
+                    bail!("--password is not supported with --stdin");
+                }
+                extract_archive_stream(output_dir)
+            } else {
+                let archive = match archive {
+                    Some(a) => a,
+                    None => bail!("--archive is required unless --stdin is set"),
+                };
+                extract_archive_dispatch(archive, output_dir, password)
+            }
+        },
+        Commands::Test { archive, password } => test_archive(archive, password),
+    }
+}
+
+/// Route a create request to the ZIP path or to the tar/codec path in
+/// `format`, based on the destination's extension.
+pub fn create_archive_dispatch(src: PathBuf, dst: PathBuf, method: u16, mode: Option<u32>, chunk: usize, password: Option<String>, level: Option<i64>) -> Result<(), Error> {
+    let fmt = Format::from_extension(&dst)
+        .with_context(|| format!("{dst:?}: not a recognized archive format (expected .zip, .tar, .tar.gz, .tar.zst, .tar.bz2, .gz, .zst, or .bz2)"))?;
+
+    match fmt {
+        Format::Zip => create_archive(src, dst, method, mode, chunk, password, level),
+        Format::Tar => format::write_tar(&src, &dst, None),
+        Format::TarGz => format::write_tar(&src, &dst, Some(Format::Gz)),
+        Format::TarZst => format::write_tar(&src, &dst, Some(Format::Zst)),
+        Format::TarBz2 => format::write_tar(&src, &dst, Some(Format::Bz2)),
+        Format::Gz | Format::Zst | Format::Bz2 => format::write_codec_stream(&src, &dst, fmt),
+    }
+}
+
+/// Route an extract request to the ZIP path or to the tar/codec path in
+/// `format`, based on the archive's extension, falling back to its magic
+/// bytes when the extension isn't recognized.
+pub fn extract_archive_dispatch(archive: PathBuf, output_dir: Option<PathBuf>, password: Option<String>) -> Result<(), Error> {
+    let fmt = match Format::from_extension(&archive) {
+        Some(fmt) => fmt,
+        None => format::sniff_magic(&archive)?
+            .with_context(|| format!("{archive:?}: not a recognized archive format"))?,
+    };
+
+    match fmt {
+        Format::Zip => extract_archive(archive, output_dir, password),
+        Format::Tar => format::extract_tar(&archive, output_dir.as_deref(), None),
+        Format::TarGz => format::extract_tar(&archive, output_dir.as_deref(), Some(Format::Gz)),
+        Format::TarZst => format::extract_tar(&archive, output_dir.as_deref(), Some(Format::Zst)),
+        Format::TarBz2 => format::extract_tar(&archive, output_dir.as_deref(), Some(Format::Bz2)),
+        Format::Gz | Format::Zst | Format::Bz2 => {
+            let dst = match output_dir {
+                Some(d) => d,
+                None => format::default_output_path(&archive, fmt)?,
+            };
+            format::extract_codec_stream(&archive, &dst, fmt)
+        }
     }
 }
 
@@ -33,14 +95,37 @@ pub enum Commands {
         #[arg(short = 'c', long = "chunk")]
         chunk: usize,
         #[arg(short = 'p', long = "password")]
-        password: Option<String>
+        password: Option<String>,
+        /// Deflate compression effort, 1-9. Ignored when `--zopfli` is set; the
+        /// level is then derived from `--zopfli-iterations`
+        #[arg(short = 'l', long = "level")]
+        level: Option<i64>,
+        /// Use the Zopfli deflate backend for maximum compression
+        #[arg(long = "zopfli")]
+        zopfli: bool,
+        /// Zopfli squeeze iterations to run per block
+        #[arg(long = "zopfli-iterations", default_value_t = 15)]
+        zopfli_iterations: u8
     },
     Unzip {
         /// Show supported features as strings and exit
         #[arg(short = 'a', long = "archive")]
-        archive: PathBuf,
+        archive: Option<PathBuf>,
         #[arg(short = 'o', long = "output")]
-        output_dir: Option<PathBuf>
+        output_dir: Option<PathBuf>,
+        #[arg(short = 'p', long = "password")]
+        password: Option<String>,
+        /// Read the archive from stdin instead of a seekable file, streaming
+        /// entries out as their local file headers arrive
+        #[arg(long = "stdin")]
+        stdin: bool
+    },
+    /// Read every entry and verify its CRC-32 without extracting, like `unzip -t`
+    Test {
+        #[arg(short = 'a', long = "archive")]
+        archive: PathBuf,
+        #[arg(short = 'p', long = "password")]
+        password: Option<String>
     }
 }
 
@@ -53,7 +138,7 @@ pub struct Args {
     pub commands: Commands,
 }
 
-pub fn extract_archive(archive: PathBuf, output_dir: Option<PathBuf>) -> Result<(), Error> {
+pub fn extract_archive(archive: PathBuf, output_dir: Option<PathBuf>, password: Option<String>) -> Result<(), Error> {
     let output_dir = if let Some(d) = output_dir {
         Some(d)
     } else {
@@ -65,35 +150,55 @@ pub fn extract_archive(archive: PathBuf, output_dir: Option<PathBuf>) -> Result<
     let mut archive = zip::ZipArchive::new(file)?;
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = match file.enclosed_name() {
-            Some(path) => {
-                if let Some(d) = &output_dir {
-                    d.join(path)
-                } else {
-                    path
-                }
-            },
-            None => continue,
+        let mut file = match &password {
+            Some(pw) => archive.by_index_decrypt(i, pw.as_bytes())?,
+            None => archive.by_index(i)?,
         };
+        write_entry(&mut file, output_dir.as_deref())?;
+    }
 
-        if file.is_dir() {
-            std::fs::create_dir_all(&outpath)?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    std::fs::create_dir_all(p)?;
-                }
-            }
-            let mut outfile = std::fs::File::create(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
+    Ok(())
+}
+
+/// Write a single archive entry to disk under `output_dir` (or the current
+/// directory), reconstructing directories and symlinks and restoring Unix
+/// permissions. Shared by the seekable (`extract_archive`) and streaming
+/// (`extract_archive_stream`) read paths.
+fn write_entry(file: &mut zip::read::ZipFile<'_>, output_dir: Option<&Path>) -> Result<(), Error> {
+    let outpath = match file.enclosed_name() {
+        Some(path) => match output_dir {
+            Some(d) => d.join(path),
+            None => path,
+        },
+        None => return Ok(()),
+    };
+
+    let is_symlink = file.unix_mode().is_some_and(|m| m & 0o170000 == 0o120000);
+
+    if let Some(p) = outpath.parent() {
+        if !p.exists() {
+            std::fs::create_dir_all(p)?;
         }
+    }
 
-        // Get and Set permissions
+    if file.is_dir() {
+        std::fs::create_dir_all(&outpath)?;
+    } else if is_symlink {
+        let mut target = String::new();
+        file.read_to_string(&mut target)?;
         #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+        std::os::unix::fs::symlink(&target, &outpath)?;
+    } else {
+        let mut outfile = std::fs::File::create(&outpath)?;
+        std::io::copy(file, &mut outfile)?;
+    }
+
+    // Get and Set permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
 
+        if !is_symlink {
             if let Some(mode) = file.unix_mode() {
                 std::fs::set_permissions(&outpath, std::fs::Permissions::from_mode(mode))?;
             }
@@ -103,16 +208,73 @@ pub fn extract_archive(archive: PathBuf, output_dir: Option<PathBuf>) -> Result<
     Ok(())
 }
 
-pub fn create_archive(src: PathBuf, dst: PathBuf, method: u16, mode: Option<u32>, chunk: usize, password: Option<String>) -> Result<(), Error> {
+/// Read every entry's full contents and let `zip` validate its CRC-32
+/// against the stored value, without writing anything to disk. Mirrors
+/// `unzip -t`.
+pub fn test_archive(archive: PathBuf, password: Option<String>) -> Result<(), Error> {
+    let file = std::fs::File::open(archive)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    let total = archive.len();
+    let mut failed = 0usize;
+    for i in 0..total {
+        let mut file = match &password {
+            Some(pw) => match archive.by_index_decrypt(i, pw.as_bytes()) {
+                Ok(file) => file,
+                Err(err) => {
+                    warn!("entry {i}: {err}");
+                    failed += 1;
+                    continue;
+                }
+            },
+            None => match archive.by_index(i) {
+                Ok(file) => file,
+                Err(err) => {
+                    warn!("entry {i}: {err}");
+                    failed += 1;
+                    continue;
+                }
+            },
+        };
+
+        let name = file.name().to_owned();
+        if let Err(err) = std::io::copy(&mut file, &mut std::io::sink()) {
+            warn!("{name}: {err}");
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {total} entries failed the integrity check");
+    }
+
+    info!("All {total} entries passed the integrity check");
+    Ok(())
+}
+
+pub fn extract_archive_stream(output_dir: Option<PathBuf>) -> Result<(), Error> {
+    let stdin = std::io::stdin();
+    let mut reader = stdin.lock();
+
+    while let Some(mut file) = zip::read::read_zipfile_from_stream(&mut reader)? {
+        write_entry(&mut file, output_dir.as_deref())?;
+    }
+
+    Ok(())
+}
+
+pub fn create_archive(src: PathBuf, dst: PathBuf, method: u16, mode: Option<u32>, chunk: usize, password: Option<String>, level: Option<i64>) -> Result<(), Error> {
     if !src.is_dir() {
-        compress_file(&src, dst, method, mode, chunk, password)?
+        compress_file(&src, dst, method, mode, chunk, password, level)?
     } else {
         let method = into_comp_method(method);
 
         let walkdir = walkdir::WalkDir::new(&src);
-    
+
         let file = std::fs::File::create(dst)?;
-        let mut options = zip::write::SimpleFileOptions::default().compression_method(method);
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level);
         let mut pw_str = String::new();
         if let Some(pw) = password {
             pw_str.push_str(&pw);
@@ -128,19 +290,27 @@ pub fn create_archive(src: PathBuf, dst: PathBuf, method: u16, mode: Option<u32>
     
         let mut buf = vec![0u8; chunk];
         for entry in walkdir.into_iter() {
-            let path = match &entry {
-                Ok(e) => e.path(),
+            let entry = match entry {
+                Ok(e) => e,
                 Err(err) => bail!("Failed to open file: {err}"),
             };
+            let path = entry.path();
             let name = path.strip_prefix(&src)?;
             let path_as_string = name
                 .to_str()
                 .map(str::to_owned)
                 .with_context(|| format!("{name:?} Is a Non UTF-8 Path"))?;
-    
-            // Write file or directory explicitly
+
+            // Write file, directory, or symlink explicitly
             // Some unzip tools unzip files with directory paths correctly, some do not!
-            if path.is_file() {
+            if entry.path_is_symlink() {
+                let target = std::fs::read_link(path)?;
+                let target_as_string = target
+                    .to_str()
+                    .map(str::to_owned)
+                    .with_context(|| format!("{target:?} Is a Non UTF-8 Path"))?;
+                zip.add_symlink(path_as_string, target_as_string, options)?;
+            } else if path.is_file() {
                 zip.start_file(path_as_string, options)?;
                 let mut f = std::fs::File::open(path)?;
 
@@ -163,13 +333,14 @@ pub fn create_archive(src: PathBuf, dst: PathBuf, method: u16, mode: Option<u32>
     Ok(())
 }
 
-fn compress_file(src: &std::path::PathBuf, dst: PathBuf, method: u16, mode: Option<u32>, chunk: usize, password: Option<String>) -> Result<(), Error> {
+fn compress_file(src: &std::path::PathBuf, dst: PathBuf, method: u16, mode: Option<u32>, chunk: usize, password: Option<String>, level: Option<i64>) -> Result<(), Error> {
     let file = std::fs::File::create(dst)?;
 
     let mut zip = zip::ZipWriter::new(file);
 
     let mut options = zip::write::SimpleFileOptions::default()
-        .compression_method(into_comp_method(method));
+        .compression_method(into_comp_method(method))
+        .compression_level(level);
     if let Some(m) = mode {
         options = options.unix_permissions(m);
     }
@@ -212,6 +383,19 @@ fn compress_file(src: &std::path::PathBuf, dst: PathBuf, method: u16, mode: Opti
     Ok(())
 }
 
+/// Resolve the `--level`/`--zopfli`/`--zopfli-iterations` flags into a
+/// single `compression_level` for `zip`. Zopfli is selected by levels above
+/// 9, with `level - 9` squeeze iterations per block, so the level this
+/// produces scales with `--zopfli-iterations` and takes precedence over an
+/// explicit `--level` when `--zopfli` is set.
+fn resolve_compression_level(level: Option<i64>, zopfli: bool, zopfli_iterations: u8) -> Option<i64> {
+    if zopfli {
+        Some(9 + zopfli_iterations as i64)
+    } else {
+        level
+    }
+}
+
 fn into_comp_method(value: u16) -> zip::CompressionMethod {
     match value {
         0 => zip::CompressionMethod::Stored,
@@ -223,4 +407,73 @@ fn into_comp_method(value: u16) -> zip::CompressionMethod {
         6 => zip::CompressionMethod::Lzma,
         _i => zip::CompressionMethod::Deflated,
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_create_then_extract() {
+        let dir = std::env::temp_dir().join(format!("zip-test-roundtrip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("hello.txt"), b"hello world").unwrap();
+
+        let archive = dir.join("out.zip");
+        create_archive(src_dir, archive.clone(), 1, None, 4096, None, None).unwrap();
+
+        let out_dir = dir.join("extracted");
+        extract_archive(archive, Some(out_dir.clone()), None).unwrap();
+
+        let contents = std::fs::read(out_dir.join("hello.txt")).unwrap();
+        assert_eq!(contents, b"hello world");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_passes_on_valid_archive() {
+        let dir = std::env::temp_dir().join(format!("zip-test-test-ok-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("hello.txt"), b"hello world").unwrap();
+
+        let archive = dir.join("out.zip");
+        create_archive(src_dir, archive.clone(), 0, None, 4096, None, None).unwrap();
+
+        test_archive(archive, None).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_archive_fails_on_corrupted_entry() {
+        let dir = std::env::temp_dir().join(format!("zip-test-test-corrupt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let src_dir = dir.join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("hello.txt"), b"hello world").unwrap();
+
+        let archive = dir.join("out.zip");
+        // Stored (method 0) keeps the entry bytes uncompressed and findable.
+        create_archive(src_dir, archive.clone(), 0, None, 4096, None, None).unwrap();
+
+        let mut bytes = std::fs::read(&archive).unwrap();
+        let offset = bytes
+            .windows(b"hello world".len())
+            .position(|w| w == b"hello world")
+            .expect("stored entry content not found in archive bytes");
+        bytes[offset] ^= 0xff;
+        std::fs::write(&archive, bytes).unwrap();
+
+        assert!(test_archive(archive, None).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
\ No newline at end of file